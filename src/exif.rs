@@ -0,0 +1,70 @@
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use exif::{In, Tag};
+use serde::Deserialize;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+}
+
+/// The subset of EXIF metadata rando-photo cares about for filtering.
+/// Individual fields degrade to `None` rather than failing the whole read,
+/// since most photo libraries are missing at least one of these tags.
+#[derive(Debug, Clone, Default)]
+pub struct ImageMetadata {
+    pub orientation: Option<Orientation>,
+    pub captured_at: Option<NaiveDateTime>,
+    pub camera: Option<String>,
+}
+
+pub fn read(path: &Path) -> ImageMetadata {
+    let Ok(file) = std::fs::File::open(path) else {
+        return ImageMetadata::default();
+    };
+    let mut reader = std::io::BufReader::new(file);
+    let exif = match exif::Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => exif,
+        Err(err) => {
+            warn!(?path, ?err, "failed to read EXIF metadata, treating as unknown");
+            return ImageMetadata::default();
+        }
+    };
+
+    let orientation = exif
+        .get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .map(|value| {
+            // EXIF orientation 1-8 describes the rotation/flip needed to
+            // display the image upright; 5-8 mean the stored raster is
+            // rotated 90/270 degrees, i.e. displayed portrait-first.
+            if (5..=8).contains(&value) {
+                Orientation::Portrait
+            } else {
+                Orientation::Landscape
+            }
+        });
+
+    let captured_at = exif
+        .get_field(Tag::DateTimeOriginal, In::PRIMARY)
+        // Parse the raw tag string: `display_value()` reformats dates.
+        .and_then(|field| match &field.value {
+            exif::Value::Ascii(values) => values.first().and_then(|raw| std::str::from_utf8(raw).ok()),
+            _ => None,
+        })
+        .and_then(|value| NaiveDateTime::parse_from_str(value, "%Y:%m:%d %H:%M:%S").ok());
+
+    let camera = exif
+        .get_field(Tag::Model, In::PRIMARY)
+        .map(|field| field.display_value().to_string());
+
+    ImageMetadata {
+        orientation,
+        captured_at,
+        camera,
+    }
+}
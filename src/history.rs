@@ -0,0 +1,74 @@
+use std::{collections::VecDeque, path::PathBuf};
+
+use axum_extra::extract::cookie::{Cookie, Key, SignedCookieJar};
+
+const COOKIE_NAME: &str = "rando_photo_recent";
+/// Separates paths within the cookie value; paths can contain commas, so a
+/// control character that can never appear in a path is used instead.
+const SEPARATOR: char = '\u{1f}';
+
+/// Ring buffer of recently served images, shared across all clients, so
+/// consecutive `/random` calls are unlikely to repeat a photo.
+#[derive(Debug, Default)]
+pub struct RecentHistory {
+    capacity: usize,
+    recent: VecDeque<PathBuf>,
+}
+
+impl RecentHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            recent: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn contains(&self, path: &PathBuf) -> bool {
+        self.recent.contains(path)
+    }
+
+    pub fn push(&mut self, path: PathBuf) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.recent.len() >= self.capacity {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(path);
+    }
+}
+
+/// Derives a signing key for the per-client history cookie from a
+/// plain-text secret. `Key::from` requires at least 64 bytes of material,
+/// so short secrets are zero-padded.
+pub fn client_key(secret: &str) -> Key {
+    let mut bytes = secret.as_bytes().to_vec();
+    bytes.resize(64, 0);
+    Key::from(&bytes)
+}
+
+pub fn read_client_history(jar: &SignedCookieJar) -> Vec<PathBuf> {
+    jar.get(COOKIE_NAME)
+        .map(|cookie| cookie.value().split(SEPARATOR).filter(|s| !s.is_empty()).map(PathBuf::from).collect())
+        .unwrap_or_default()
+}
+
+/// Appends `shown` to `history`, trims it to `capacity`, and returns a jar
+/// with the updated cookie set.
+pub fn write_client_history(
+    jar: SignedCookieJar,
+    mut history: Vec<PathBuf>,
+    capacity: usize,
+    shown: PathBuf,
+) -> SignedCookieJar {
+    history.push(shown);
+    while history.len() > capacity {
+        history.remove(0);
+    }
+    let value = history
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(&SEPARATOR.to_string());
+    jar.add(Cookie::new(COOKIE_NAME, value))
+}
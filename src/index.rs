@@ -0,0 +1,185 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use glob::{MatchOptions, glob_with};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::{Args, NewestSort, exif};
+
+/// How long to wait for watch events to stop arriving before rebuilding,
+/// so a burst of changes (e.g. a bulk copy) triggers one rescan instead of
+/// one per file.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// An indexed image paired with the EXIF metadata used to filter/sort it.
+#[derive(Debug, Clone)]
+pub struct ImageEntry {
+    pub path: PathBuf,
+    pub metadata: exif::ImageMetadata,
+}
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// In-memory snapshot of the image tree, built once at startup (and
+/// whenever `images_path` changes) so request handlers never glob or stat
+/// the filesystem themselves.
+#[derive(Debug, Default)]
+pub struct ImageIndex {
+    /// Result of `fast_glob`, used by `newest_image_handler`.
+    pub fast: Vec<ImageEntry>,
+    /// Result of `final_glob`, used by `random_image_handler`.
+    pub final_: Vec<ImageEntry>,
+    /// Newest entry of `fast`, precomputed so the handler never has to
+    /// touch file metadata. Ordered by EXIF capture time or by mtime
+    /// (modification time, universally supported unlike creation time),
+    /// depending on `args.newest_sort`.
+    pub newest: Option<ImageEntry>,
+}
+
+impl ImageIndex {
+    pub fn build(args: &Args) -> Self {
+        let fast = glob_images(&args.images_path, &args.fast_glob, &args.extensions);
+        let final_ = glob_images(&args.images_path, &args.final_glob, &args.extensions);
+        let newest = newest_of(&fast, args.newest_sort);
+        Self {
+            fast,
+            final_,
+            newest,
+        }
+    }
+}
+
+/// Globs every file under `pattern` whose extension matches `extensions`
+/// (case-insensitively), reading EXIF metadata for each. Files with a
+/// missing or unrecognized extension are sniffed by magic bytes so formats
+/// like a misnamed `.jpeg` as `.img` are still picked up without being
+/// confused for unrelated files.
+fn glob_images(images_path: &Path, pattern: &Path, extensions: &[String]) -> Vec<ImageEntry> {
+    let root = images_path.join(pattern);
+    let glob_pattern = root.join("**/*");
+    let Ok(entries) = glob_with(&glob_pattern.to_string_lossy(), GLOB_OPTIONS) else {
+        warn!(?glob_pattern, "invalid glob pattern, index will be empty");
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| match entry {
+            Ok(path) => Some(path),
+            Err(err) => {
+                warn!(?err, "failed to read glob entry, skipping");
+                None
+            }
+        })
+        .filter(|path| path.is_file() && is_image(path, extensions))
+        .map(|path| {
+            let metadata = exif::read(&path);
+            ImageEntry { path, metadata }
+        })
+        .collect()
+}
+
+fn is_image(path: &Path, extensions: &[String]) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if extensions.iter().any(|known| known.eq_ignore_ascii_case(ext)) => true,
+        _ => infer::get_from_path(path)
+            .ok()
+            .flatten()
+            .is_some_and(|kind| kind.matcher_type() == infer::MatcherType::Image),
+    }
+}
+
+fn newest_of(images: &[ImageEntry], sort: NewestSort) -> Option<ImageEntry> {
+    match sort {
+        NewestSort::Exif => images
+            .iter()
+            .filter(|entry| entry.metadata.captured_at.is_some())
+            .max_by_key(|entry| entry.metadata.captured_at)
+            .cloned()
+            .or_else(|| newest_of(images, NewestSort::Mtime)),
+        NewestSort::Mtime => images
+            .iter()
+            .filter_map(|entry| {
+                let modified = entry.path.metadata().and_then(|m| m.modified()).ok()?;
+                Some((entry, modified))
+            })
+            .max_by_key(|(_, modified)| *modified)
+            .map(|(entry, _)| entry.clone()),
+    }
+}
+
+/// Watches `images_path` for filesystem changes and rebuilds the index in
+/// place whenever something changes, so handlers keep serving a stale but
+/// never-blocking view in between.
+pub fn spawn_watcher(args: Args, index: Arc<RwLock<ImageIndex>>) {
+    let (tx, mut rx) = mpsc::channel(16);
+    let mut watcher = match RecommendedWatcher::new(
+        move |event| {
+            let _ = tx.blocking_send(event);
+        },
+        notify::Config::default(),
+    ) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            error!(?err, "failed to create filesystem watcher, index will not auto-refresh");
+            return;
+        }
+    };
+
+    if let Err(err) = watcher.watch(&args.images_path, RecursiveMode::Recursive) {
+        error!(?err, "failed to watch images_path, index will not auto-refresh");
+        return;
+    }
+
+    tokio::spawn(async move {
+        // Keep the watcher alive for as long as this task runs.
+        let _watcher = watcher;
+        let mut dirty = false;
+        loop {
+            let event = if dirty {
+                match tokio::time::timeout(DEBOUNCE, rx.recv()).await {
+                    Ok(event) => event,
+                    Err(_elapsed) => {
+                        dirty = false;
+                        info!("images_path changed, rebuilding index");
+                        let args = args.clone();
+                        let rebuilt = match tokio::task::spawn_blocking(move || ImageIndex::build(&args)).await {
+                            Ok(rebuilt) => rebuilt,
+                            Err(err) => {
+                                warn!(?err, "index rebuild task panicked, skipping rebuild");
+                                continue;
+                            }
+                        };
+                        match index.write() {
+                            Ok(mut guard) => *guard = rebuilt,
+                            Err(err) => warn!(?err, "index lock poisoned, skipping rebuild"),
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                rx.recv().await
+            };
+
+            let Some(event) = event else {
+                break;
+            };
+
+            match event {
+                Ok(event) if event.kind.is_create() || event.kind.is_remove() || event.kind.is_modify() => {
+                    dirty = true;
+                }
+                Ok(_) => {}
+                Err(err) => warn!(?err, "filesystem watch error"),
+            }
+        }
+    });
+}
@@ -1,18 +1,36 @@
-use std::path::PathBuf;
+mod exif;
+mod history;
+mod index;
+mod serve;
+mod telemetry;
+mod thumbnail;
+mod upload;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
 
 use axum::{
     Extension, Router,
-    http::StatusCode,
+    extract::{DefaultBodyLimit, Multipart, Query},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Redirect, Response},
-    routing::get,
+    routing::{get, post},
 };
-use clap::Parser;
-use glob::{MatchOptions, glob_with};
+use axum_extra::extract::cookie::SignedCookieJar;
+use chrono::NaiveDate;
+use clap::{Parser, ValueEnum};
 use pathdiff::diff_paths;
 use rand::seq::IndexedRandom;
+use serde::Deserialize;
 use tokio::signal;
-use tower_http::services::ServeDir;
-use tracing_subscriber::FmtSubscriber;
+use tower_http::{services::ServeDir, trace::TraceLayer};
+
+use exif::Orientation;
+use history::RecentHistory;
+use index::{ImageEntry, ImageIndex};
+use thumbnail::ThumbParams;
 
 #[derive(Debug, Parser, Clone)]
 struct Args {
@@ -24,18 +42,167 @@ struct Args {
     pub final_glob: PathBuf,
     #[clap(long, env = "HTTP_ADDRESS", default_value = "0.0.0.0:3000")]
     pub http_address: String,
+    #[clap(long, env = "THUMBNAIL_CACHE_DIR", default_value = "thumbnail-cache")]
+    pub thumbnail_cache_dir: PathBuf,
+    /// Whether `/random` and `/newest` redirect into `/images/` or stream
+    /// the selected file themselves with caching headers.
+    #[clap(long, env = "SERVE_MODE", value_enum, default_value = "redirect")]
+    pub serve_mode: ServeMode,
+    /// File extensions considered images when building the index. Files
+    /// with a missing or unrecognized extension still get a chance via
+    /// magic-byte sniffing.
+    #[clap(
+        long,
+        env = "EXTENSIONS",
+        value_delimiter = ',',
+        default_value = "jpg,jpeg,png,webp,avif,heic"
+    )]
+    pub extensions: Vec<String>,
+    /// Whether `/newest` picks the file with the latest filesystem mtime
+    /// or the latest EXIF `DateTimeOriginal`. With `exif`, images without
+    /// that tag are not considered; mtime is used only when no indexed
+    /// image has the tag.
+    #[clap(long, env = "NEWEST_SORT", value_enum, default_value = "mtime")]
+    pub newest_sort: NewestSort,
+    /// OTLP gRPC endpoint (e.g. `http://localhost:4317`) to export traces
+    /// to. Traces stay local-only (stdout logs) when unset.
+    #[clap(long, env = "OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+    /// Subdirectory under `images_path` that `POST /upload` writes new
+    /// images into.
+    #[clap(long, env = "UPLOAD_SUBDIR", default_value = "uploads")]
+    pub upload_subdir: PathBuf,
+    /// Bearer token required on `POST /upload`. Uploads are rejected
+    /// entirely when unset, so the endpoint is opt-in.
+    #[clap(long, env = "UPLOAD_TOKEN")]
+    pub upload_token: Option<String>,
+    /// Size of the "recently served" ring buffer `/random` avoids
+    /// repeating from, both globally and per-client when `cookie_secret`
+    /// is set.
+    #[clap(long, env = "RECENT_HISTORY_SIZE", default_value_t = 10)]
+    pub recent_history_size: usize,
+    /// Secret used to sign the optional per-client `/random` history
+    /// cookie, giving each viewer their own shuffle sequence. Without it,
+    /// only the shared ring buffer is used.
+    #[clap(long, env = "COOKIE_SECRET")]
+    pub cookie_secret: Option<String>,
+    /// Maximum accepted size, in bytes, of a `POST /upload` request body.
+    /// Raised above axum's 2MB default since real photos routinely exceed
+    /// it.
+    #[clap(long, env = "UPLOAD_MAX_BYTES", default_value_t = 50 * 1024 * 1024)]
+    pub upload_max_bytes: usize,
+    /// Maximum `w`/`h` accepted by `/thumb`, rejecting larger requests
+    /// before they reach `image::resize` or the disk cache. Keeps an
+    /// unauthenticated caller from forcing expensive resizes or filling
+    /// `thumbnail_cache_dir` with one-off cache entries.
+    #[clap(long, env = "MAX_THUMBNAIL_DIMENSION", default_value_t = 4096)]
+    pub max_thumbnail_dimension: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ServeMode {
+    Redirect,
+    Direct,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NewestSort {
+    Mtime,
+    Exif,
+}
+
+/// Query params accepted on `/random` to sample only from a matching
+/// subset of the index.
+#[derive(Debug, Deserialize)]
+struct RandomFilterQuery {
+    orientation: Option<Orientation>,
+    camera: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_date")]
+    after: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "deserialize_date")]
+    before: Option<NaiveDate>,
+}
+
+fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw: Option<String> = Option::deserialize(deserializer)?;
+    match raw {
+        Some(raw) if !raw.is_empty() => NaiveDate::parse_from_str(&raw, "%Y-%m-%d")
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+/// Whether `entry` matches every filter in `filter` that was actually set.
+fn matches_filter(entry: &ImageEntry, filter: &RandomFilterQuery) -> bool {
+    if let Some(wanted) = filter.orientation {
+        if entry.metadata.orientation != Some(wanted) {
+            return false;
+        }
+    }
+    if let Some(wanted) = &filter.camera {
+        if entry.metadata.camera.as_deref() != Some(wanted.as_str()) {
+            return false;
+        }
+    }
+    if let Some(after) = filter.after {
+        if entry.metadata.captured_at.is_none_or(|captured| captured.date() < after) {
+            return false;
+        }
+    }
+    if let Some(before) = filter.before {
+        if entry.metadata.captured_at.is_none_or(|captured| captured.date() > before) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Optional thumbnail dimensions accepted on `/random` and `/newest`.
+#[derive(Debug, Deserialize)]
+struct ThumbSizeQuery {
+    w: Option<u32>,
+    h: Option<u32>,
+    format: Option<String>,
+}
+
+/// Query params for the `/thumb` endpoint itself.
+#[derive(Debug, Deserialize)]
+struct ThumbQuery {
+    path: String,
+    w: u32,
+    h: u32,
+    format: Option<String>,
+}
+
+fn parse_format(format: Option<&str>) -> image::ImageFormat {
+    match format {
+        Some("png") => image::ImageFormat::Png,
+        Some("webp") => image::ImageFormat::WebP,
+        _ => image::ImageFormat::Jpeg,
+    }
+}
+
+/// Resolves a client-supplied relative path against `images_path`,
+/// rejecting anything that escapes it (e.g. via `..`).
+fn resolve_source(images_path: &Path, relative: &str) -> Option<PathBuf> {
+    let root = images_path.canonicalize().ok()?;
+    let candidate = root.join(relative).canonicalize().ok()?;
+    candidate.starts_with(&root).then_some(candidate)
 }
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
     let args = Args::parse();
-    FmtSubscriber::builder()
-        .with_max_level(tracing::Level::INFO)
-        .with_target(true)
-        .with_file(true)
-        .with_line_number(true)
-        .init();
+    telemetry::init(&args);
+
+    let index = Arc::new(RwLock::new(ImageIndex::build(&args)));
+    index::spawn_watcher(args.clone(), index.clone());
+    let history = Arc::new(Mutex::new(RecentHistory::new(args.recent_history_size)));
 
     let listener = tokio::net::TcpListener::bind(args.http_address.clone())
         .await
@@ -43,7 +210,15 @@ async fn main() {
     let app = Router::new()
         .route("/random", get(random_image_handler))
         .route("/newest", get(newest_image_handler))
+        .route("/thumb", get(thumb_handler))
+        .route(
+            "/upload",
+            post(upload_handler).layer(DefaultBodyLimit::max(args.upload_max_bytes)),
+        )
         .nest_service("/images/", ServeDir::new(args.images_path.clone()))
+        .layer(TraceLayer::new_for_http())
+        .layer(Extension(history))
+        .layer(Extension(index))
         .layer(Extension(args));
     axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal())
@@ -51,67 +226,214 @@ async fn main() {
         .unwrap();
 }
 
-async fn random_image_handler(Extension(args): Extension<Args>) -> Response {
-    let image = random_image(args.images_path.join(args.final_glob));
-    if let Some(image) = image {
-        println!("Image: {:?}", image);
-        Redirect::temporary(&format!(
-            "/images/{}",
-            diff_paths(image, args.images_path)
-                .unwrap()
-                .to_string_lossy()
-        ))
-        .into_response()
-    } else {
-        println!("No image found");
-        (StatusCode::NOT_FOUND, "No image found".to_string()).into_response()
+#[tracing::instrument(skip_all)]
+async fn random_image_handler(
+    Extension(args): Extension<Args>,
+    Extension(index): Extension<Arc<RwLock<ImageIndex>>>,
+    Extension(history): Extension<Arc<Mutex<RecentHistory>>>,
+    Query(size): Query<ThumbSizeQuery>,
+    Query(filter): Query<RandomFilterQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let client_key = args.cookie_secret.as_deref().map(history::client_key);
+    let jar = client_key.map(|key| SignedCookieJar::from_headers(&headers, key));
+    let client_seen: Vec<PathBuf> = jar.as_ref().map(history::read_client_history).unwrap_or_default();
+
+    let image = {
+        let guard = index.read().unwrap();
+        let matching: Vec<&ImageEntry> = guard.final_.iter().filter(|entry| matches_filter(entry, &filter)).collect();
+
+        let recent = history.lock().unwrap();
+        let fresh: Vec<&ImageEntry> = matching
+            .iter()
+            .copied()
+            .filter(|entry| !recent.contains(&entry.path) && !client_seen.contains(&entry.path))
+            .collect();
+        let pool: &[&ImageEntry] = if fresh.is_empty() { &matching } else { &fresh };
+        pool.choose(&mut rand::rng()).map(|entry| entry.path.clone())
+    };
+
+    let Some(image) = image else {
+        tracing::warn!("no image found for /random");
+        return (StatusCode::NOT_FOUND, "No image found".to_string()).into_response();
+    };
+
+    history.lock().unwrap().push(image.clone());
+    tracing::info!(path = %image.display(), "serving random image");
+    let response = respond_with_image(&args, image.clone(), &size, &headers).await;
+
+    match jar {
+        Some(jar) => {
+            let jar = history::write_client_history(jar, client_seen, args.recent_history_size, image);
+            (jar, response).into_response()
+        }
+        None => response,
     }
 }
 
-async fn newest_image_handler(Extension(args): Extension<Args>) -> Response {
-    let image = newest_image(args.images_path.join(args.fast_glob));
+#[tracing::instrument(skip_all)]
+async fn newest_image_handler(
+    Extension(args): Extension<Args>,
+    Extension(index): Extension<Arc<RwLock<ImageIndex>>>,
+    Query(size): Query<ThumbSizeQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let image = index.read().unwrap().newest.as_ref().map(|entry| entry.path.clone());
     if let Some(image) = image {
-        println!("Image: {:?}", image);
-        Redirect::temporary(&format!(
-            "/images/{}",
-            diff_paths(image, args.images_path)
-                .unwrap()
-                .to_string_lossy()
-        ))
-        .into_response()
+        tracing::info!(path = %image.display(), "serving newest image");
+        respond_with_image(&args, image, &size, &headers).await
     } else {
-        println!("No image found");
+        tracing::warn!("no image found for /newest");
         (StatusCode::NOT_FOUND, "No image found".to_string()).into_response()
     }
 }
 
-fn random_image(path: PathBuf) -> Option<PathBuf> {
-    let all_images = glob_with(
-        &path.join("**/*.jpg").to_string_lossy(),
-        MatchOptions {
-            case_sensitive: false,
-            ..Default::default()
-        },
-    )
-    .unwrap();
-    let images: Vec<PathBuf> = all_images.map(|x| x.unwrap()).collect();
-    images.choose(&mut rand::rng()).map(|i| i.to_path_buf())
-}
-
-fn newest_image(path: PathBuf) -> Option<PathBuf> {
-    let all_images = glob_with(
-        &path.join("**/*.jpg").to_string_lossy(),
-        MatchOptions {
-            case_sensitive: false,
-            ..Default::default()
-        },
-    )
-    .unwrap();
-    let images: Vec<PathBuf> = all_images.map(|x| x.unwrap()).collect();
-    images
-        .iter()
-        .max_by_key(|x| x.metadata().unwrap().created().unwrap())
-        .map(|i| i.to_path_buf())
+/// Redirects to `/thumb` when the caller asked for a resized version via
+/// `?w=&h=`. Otherwise either redirects into `/images/` or streams the
+/// original file directly, depending on `args.serve_mode`.
+async fn respond_with_image(args: &Args, image: PathBuf, size: &ThumbSizeQuery, headers: &HeaderMap) -> Response {
+    let relative = diff_paths(&image, &args.images_path)
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+
+    if let (Some(w), Some(h)) = (size.w, size.h) {
+        let mut url = format!("/thumb?path={}&w={w}&h={h}", urlencoding_path(&relative));
+        if let Some(format) = &size.format {
+            url.push_str(&format!("&format={format}"));
+        }
+        return Redirect::temporary(&url).into_response();
+    }
+
+    match args.serve_mode {
+        ServeMode::Direct => serve::serve_file(&image, headers).await,
+        ServeMode::Redirect => Redirect::temporary(&format!("/images/{relative}")).into_response(),
+    }
+}
+
+/// Percent-encodes a relative path for safe inclusion in a query string.
+fn urlencoding_path(path: &str) -> String {
+    path.chars()
+        .flat_map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '/' => c.to_string(),
+            _ => c
+                .to_string()
+                .as_bytes()
+                .iter()
+                .map(|byte| format!("%{byte:02X}"))
+                .collect(),
+        })
+        .collect()
+}
+
+#[tracing::instrument(skip(args))]
+async fn thumb_handler(Extension(args): Extension<Args>, Query(query): Query<ThumbQuery>) -> Response {
+    if query.w > args.max_thumbnail_dimension || query.h > args.max_thumbnail_dimension {
+        return (StatusCode::BAD_REQUEST, "Requested thumbnail dimensions too large".to_string()).into_response();
+    }
+
+    let Some(source) = resolve_source(&args.images_path, &query.path) else {
+        return (StatusCode::NOT_FOUND, "No image found".to_string()).into_response();
+    };
+
+    let params = ThumbParams {
+        width: query.w,
+        height: query.h,
+        format: parse_format(query.format.as_deref()),
+    };
+
+    let cache_dir = args.thumbnail_cache_dir.clone();
+    let result = tokio::task::spawn_blocking(move || thumbnail::get_or_create(&cache_dir, &source, params))
+        .await
+        .expect("thumbnail generation task panicked");
+
+    match result {
+        Ok((bytes, mime)) => ([(header::CONTENT_TYPE, mime)], bytes).into_response(),
+        Err(err) => {
+            tracing::warn!(?err, "failed to generate thumbnail");
+            (StatusCode::NOT_FOUND, "No image found".to_string()).into_response()
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn upload_handler(
+    Extension(args): Extension<Args>,
+    Extension(index): Extension<Arc<RwLock<ImageIndex>>>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Response {
+    if !is_authorized(&args, &headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let dest_dir = args.images_path.join(&args.upload_subdir);
+    let mut stored = Vec::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!(?err, "failed to read multipart field");
+                remove_stored(&stored);
+                return (StatusCode::BAD_REQUEST, "Invalid multipart body".to_string()).into_response();
+            }
+        };
+        let bytes = match field.bytes().await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                tracing::warn!(?err, "failed to read multipart field body");
+                remove_stored(&stored);
+                return (StatusCode::BAD_REQUEST, "Invalid multipart body".to_string()).into_response();
+            }
+        };
+        match upload::store(&dest_dir, &bytes) {
+            Ok(path) => stored.push(path),
+            Err(err) => {
+                tracing::warn!(?err, "rejected upload, not a recognized image");
+                remove_stored(&stored);
+                return (StatusCode::UNPROCESSABLE_ENTITY, "Not an image".to_string()).into_response();
+            }
+        }
+    }
+
+    if stored.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No file provided".to_string()).into_response();
+    }
+
+    let rebuilt = {
+        let args = args.clone();
+        tokio::task::spawn_blocking(move || ImageIndex::build(&args))
+            .await
+            .expect("index rebuild task panicked")
+    };
+    *index.write().unwrap() = rebuilt;
+    tracing::info!(count = stored.len(), "stored uploaded image(s) and refreshed index");
+    StatusCode::CREATED.into_response()
+}
+
+/// Deletes files already written by earlier fields in a multipart batch
+/// once a later field fails, so a rejected upload doesn't leave a partial
+/// batch behind and the request is all-or-nothing from the client's view.
+fn remove_stored(stored: &[PathBuf]) {
+    for path in stored {
+        if let Err(err) = std::fs::remove_file(path) {
+            tracing::warn!(?path, ?err, "failed to roll back partially stored upload");
+        }
+    }
+}
+
+/// Checks the `Authorization: Bearer <token>` header against
+/// `args.upload_token`. Always denies when no token is configured.
+fn is_authorized(args: &Args, headers: &HeaderMap) -> bool {
+    let Some(expected) = &args.upload_token else {
+        return false;
+    };
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token == expected)
 }
 
 async fn shutdown_signal() {
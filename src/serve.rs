@@ -0,0 +1,79 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use httpdate::fmt_http_date;
+
+const MAX_AGE_SECONDS: u64 = 3600;
+
+/// Reads `path` and builds a response with `Content-Type`, `Cache-Control`,
+/// `Last-Modified` and `ETag`, honoring `If-None-Match`/`If-Modified-Since`
+/// from `request_headers` with a 304 when the client's copy is still fresh.
+pub async fn serve_file(path: &Path, request_headers: &HeaderMap) -> Response {
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(_) => return (StatusCode::NOT_FOUND, "No image found".to_string()).into_response(),
+    };
+
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let etag = etag_for(path, metadata.len(), modified);
+    let last_modified = fmt_http_date(modified);
+
+    if is_not_modified(request_headers, &etag, modified) {
+        return StatusCode::NOT_MODIFIED.into_response();
+    }
+
+    let bytes = match tokio::fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(_) => return (StatusCode::NOT_FOUND, "No image found".to_string()).into_response(),
+    };
+
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(mime.as_ref()).unwrap());
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&format!("public, max-age={MAX_AGE_SECONDS}")).unwrap(),
+    );
+    headers.insert(header::LAST_MODIFIED, HeaderValue::from_str(&last_modified).unwrap());
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+
+    (headers, bytes).into_response()
+}
+
+fn etag_for(path: &Path, size: u64, modified: std::time::SystemTime) -> String {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    size.hash(&mut hasher);
+    modified.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+fn is_not_modified(request_headers: &HeaderMap, etag: &str, modified: std::time::SystemTime) -> bool {
+    if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+        return if_none_match.to_str().map(|v| v == etag).unwrap_or(false);
+    }
+
+    if let Some(if_modified_since) = request_headers.get(header::IF_MODIFIED_SINCE) {
+        let since = if_modified_since
+            .to_str()
+            .ok()
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+        if let Some(since) = since {
+            // HTTP dates carry whole seconds only, so compare at that
+            // precision or a sub-second mtime always looks newer.
+            let secs = |time: std::time::SystemTime| time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            return secs(modified) <= secs(since);
+        }
+    }
+
+    false
+}
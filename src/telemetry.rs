@@ -0,0 +1,38 @@
+use opentelemetry::global;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime::Tokio};
+use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::Args;
+
+/// Initializes the global tracing subscriber: always a formatted stdout
+/// layer, plus an OTLP exporter when `--otlp-endpoint` is set so traces can
+/// be shipped to a collector when running the service deployed.
+pub fn init(args: &Args) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let fmt_layer = fmt::layer().with_target(true).with_file(true).with_line_number(true);
+    let registry = tracing_subscriber::registry().with(filter).with(fmt_layer);
+
+    let Some(endpoint) = &args.otlp_endpoint else {
+        registry.init();
+        return;
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .install_batch(Tokio);
+
+    match tracer {
+        Ok(tracer) => {
+            let otel_layer = tracing_opentelemetry::layer().with_tracer(tracer);
+            registry.with(otel_layer).init();
+        }
+        Err(err) => {
+            registry.init();
+            tracing::error!(?err, ?endpoint, "failed to initialize OTLP exporter, continuing without it");
+        }
+    }
+}
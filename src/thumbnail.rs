@@ -0,0 +1,77 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    io::Cursor,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use image::{ImageFormat, imageops::FilterType};
+use tracing::warn;
+
+/// Requested thumbnail dimensions and output format, parsed from the
+/// `/thumb` query string.
+#[derive(Debug, Clone, Copy)]
+pub struct ThumbParams {
+    pub width: u32,
+    pub height: u32,
+    pub format: ImageFormat,
+}
+
+#[derive(Debug)]
+pub enum ThumbnailError {
+    SourceUnreadable(std::io::Error),
+    Decode(image::ImageError),
+    Encode(image::ImageError),
+}
+
+/// Generates (or reuses a disk-cached) thumbnail for `source`, returning
+/// the encoded bytes and their MIME type. The cache key folds in the
+/// source's mtime so an edited source never serves a stale thumbnail.
+pub fn get_or_create(
+    cache_dir: &Path,
+    source: &Path,
+    params: ThumbParams,
+) -> Result<(Vec<u8>, &'static str), ThumbnailError> {
+    let mime = params.format.to_mime_type();
+    let cache_path = cache_path(cache_dir, source, params).map_err(ThumbnailError::SourceUnreadable)?;
+
+    if let Ok(cached) = std::fs::read(&cache_path) {
+        return Ok((cached, mime));
+    }
+
+    let image = image::open(source).map_err(ThumbnailError::Decode)?;
+    let resized = image.resize(params.width, params.height, FilterType::Lanczos3);
+
+    let mut bytes = Vec::new();
+    resized
+        .write_to(&mut Cursor::new(&mut bytes), params.format)
+        .map_err(ThumbnailError::Encode)?;
+
+    if let Err(err) = std::fs::create_dir_all(cache_dir).and_then(|()| std::fs::write(&cache_path, &bytes)) {
+        // A failed cache write shouldn't fail the request, we still have
+        // the freshly generated bytes to serve.
+        warn!(?err, ?cache_path, "failed to write thumbnail cache entry");
+    }
+
+    Ok((bytes, mime))
+}
+
+fn cache_path(cache_dir: &Path, source: &Path, params: ThumbParams) -> std::io::Result<PathBuf> {
+    let mtime = source
+        .metadata()?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    mtime.hash(&mut hasher);
+    params.width.hash(&mut hasher);
+    params.height.hash(&mut hasher);
+    params.format.extensions_str()[0].hash(&mut hasher);
+    let key = hasher.finish();
+
+    Ok(cache_dir.join(format!("{key:016x}.{}", params.format.extensions_str()[0])))
+}
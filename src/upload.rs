@@ -0,0 +1,24 @@
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum UploadError {
+    NotAnImage,
+    Io(std::io::Error),
+}
+
+/// Validates that `bytes` look like a real image by magic bytes, then
+/// writes them under `dest_dir` with a generated filename so a
+/// client-supplied name can't collide with or escape the directory.
+pub fn store(dest_dir: &Path, bytes: &[u8]) -> Result<PathBuf, UploadError> {
+    let kind = infer::get(bytes).filter(|kind| kind.matcher_type() == infer::MatcherType::Image);
+    let Some(kind) = kind else {
+        return Err(UploadError::NotAnImage);
+    };
+
+    std::fs::create_dir_all(dest_dir).map_err(UploadError::Io)?;
+    let path = dest_dir.join(format!("{}.{}", Uuid::new_v4(), kind.extension()));
+    std::fs::write(&path, bytes).map_err(UploadError::Io)?;
+    Ok(path)
+}